@@ -1,5 +1,5 @@
-use clap::Parser;
-use regex::Regex;
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::{env, fs, path::Path, process};
 
@@ -14,142 +14,760 @@ use std::{env, fs, path::Path, process};
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args {
-    /// Path to the input c_cpp_properties.json file.
-    #[arg(short, long, default_value = ".vscode/c_cpp_properties.json")]
-    input: String,
+    /// Path to the input c_cpp_properties.json file. When omitted, the
+    /// `GENCOMP_CONFIG` environment variable is used if set, otherwise the
+    /// tool searches for `.vscode/c_cpp_properties.json` in the current
+    /// directory and each parent up to a `.git` boundary or the filesystem
+    /// root.
+    #[arg(short, long)]
+    input: Option<String>,
 
     /// Output path for the resulting compile_commands.json.
     #[arg(short, long, default_value = "./compile_commands.json")]
     output: String,
 
+    /// Shape of each generated entry: a shell `command` string, or a pre-split
+    /// `arguments` array (plus `output`) as preferred by tools like clangd.
+    #[arg(short, long, value_enum, default_value_t = Format::Command)]
+    format: Format,
+
+    /// Name of the configuration to use (matches a configuration's "name"
+    /// field). Required when the input file has more than one configuration,
+    /// unless `--all` is given.
+    #[arg(short, long, conflicts_with = "all")]
+    config: Option<String>,
+
+    /// Process every configuration instead of just one, concatenating their
+    /// entries and deduplicating identical `file`+`command` pairs.
+    #[arg(long, conflicts_with = "config")]
+    all: bool,
+
+    /// Value to substitute for `${workspaceFolder}` (and the basis for
+    /// `${workspaceFolderBasename}`) in config fields. Defaults to the
+    /// project root implied by the input file, i.e. the parent of its
+    /// containing `.vscode` directory (or the input file's own parent
+    /// directory, if it isn't inside one).
+    #[arg(long)]
+    workspace: Option<String>,
+
     /// Optional list of source files. If provided, "generate mode" is used.
     #[arg(value_name = "SOURCES")]
     sources: Vec<String>,
 }
 
-/// Remove both single-line (//…) and multi-line (/*…*/) comments from a string.
-fn remove_comments(text: &str) -> String {
-    let re = Regex::new(r"//.*?$|/\*.*?\*/").unwrap();
-    re.replace_all(text, "").to_string()
+impl Args {
+    /// The resolved input path, after `--input`/`GENCOMP_CONFIG`/discovery
+    /// has been applied by `resolve_input`. Panics if called before that.
+    fn input_path(&self) -> &str {
+        self.input.as_deref().expect("input must be resolved before use")
+    }
 }
 
-fn main() {
-    let args = Args::parse();
+/// The default search filename, relative to a candidate directory.
+const DEFAULT_CONFIG_RELATIVE_PATH: &str = ".vscode/c_cpp_properties.json";
 
-    // Read the input file.
-    if !Path::new(&args.input).exists() {
-        eprintln!("Error: The file {} does not exist.", args.input);
-        process::exit(1);
+/// Walk upward from the current directory looking for
+/// `.vscode/c_cpp_properties.json`, stopping at a `.git` boundary or the
+/// filesystem root.
+fn discover_config() -> Option<String> {
+    let mut dir = env::current_dir().ok()?;
+    loop {
+        let candidate = dir.join(DEFAULT_CONFIG_RELATIVE_PATH);
+        if candidate.exists() {
+            return Some(candidate.to_string_lossy().into_owned());
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+        if !dir.pop() {
+            return None;
+        }
     }
-    let content = fs::read_to_string(&args.input).unwrap_or_else(|err| {
-        eprintln!("Error reading {}: {}", args.input, err);
-        process::exit(1);
-    });
-    let cleaned = remove_comments(&content);
+}
 
-    // Parse the JSON.
-    let data: Value = serde_json::from_str(&cleaned).unwrap_or_else(|err| {
-        eprintln!("Error parsing JSON from {}: {}", args.input, err);
-        process::exit(1);
-    });
+/// The precedence chain itself: an explicit `--input`, then `GENCOMP_CONFIG`,
+/// then upward discovery, falling back to the historical default so error
+/// messages still point somewhere sensible. Takes the env/discovery results
+/// as plain values so the ordering can be unit-tested without touching
+/// process environment or the filesystem.
+fn resolve_input_from(explicit: Option<String>, env_config: Option<String>, discovered: Option<String>) -> String {
+    explicit
+        .or(env_config)
+        .or(discovered)
+        .unwrap_or_else(|| DEFAULT_CONFIG_RELATIVE_PATH.to_string())
+}
 
-    // Our final compile commands will be collected here.
-    let mut compile_commands = Vec::new();
+/// Resolve the input path to use: an explicit `--input`, then
+/// `GENCOMP_CONFIG`, then upward discovery, falling back to the historical
+/// default so error messages still point somewhere sensible.
+fn resolve_input(explicit: Option<String>) -> String {
+    resolve_input_from(explicit, env::var("GENCOMP_CONFIG").ok(), discover_config())
+}
 
-    // If the top-level JSON is an array, assume it’s already a list of compile commands.
-    if let Some(arr) = data.as_array() {
-        compile_commands = arr.clone();
+#[cfg(test)]
+mod resolve_input_tests {
+    use super::*;
+
+    #[test]
+    fn explicit_input_wins_over_everything() {
+        assert_eq!(
+            resolve_input_from(Some("explicit.json".to_string()), Some("env.json".to_string()), Some("discovered.json".to_string())),
+            "explicit.json"
+        );
     }
-    // Otherwise, if it is an object with "configurations", process it.
-    else if let Some(obj) = data.as_object() {
-        if let Some(configs) = obj.get("configurations").and_then(Value::as_array) {
-            if configs.is_empty() {
-                eprintln!("Error: No configurations found in {}.", args.input);
-                process::exit(1);
-            }
-            // For simplicity, use the first configuration.
-            let config = &configs[0];
-
-            // If the configuration contains a "compileCommands" key, use merge mode.
-            if let Some(cc_field) = config.get("compileCommands") {
-                // Two cases:
-                // 1. If cc_field is an array and its first element is a string,
-                //    treat each element as a file path.
-                // 2. Otherwise, assume cc_field is directly an array of compile command objects.
-                if let Some(arr) = cc_field.as_array() {
-                    if !arr.is_empty() && arr[0].is_string() {
-                        // Each element is a file path.
-                        for file_val in arr {
-                            if let Some(path_str) = file_val.as_str() {
-                                if !Path::new(path_str).exists() {
-                                    eprintln!("Warning: Referenced file {} does not exist. Skipping.", path_str);
-                                    continue;
-                                }
-                                let file_content = fs::read_to_string(path_str).unwrap_or_else(|err| {
-                                    eprintln!("Error reading {}: {}", path_str, err);
-                                    process::exit(1);
-                                });
-                                let file_cleaned = remove_comments(&file_content);
-                                let cc_entries: Value = serde_json::from_str(&file_cleaned).unwrap_or_else(|err| {
-                                    eprintln!("Error parsing JSON from {}: {}", path_str, err);
-                                    process::exit(1);
-                                });
-                                if let Some(entries_arr) = cc_entries.as_array() {
-                                    compile_commands.extend(entries_arr.clone());
-                                } else {
-                                    eprintln!("Error: {} does not contain a JSON array.", path_str);
-                                    process::exit(1);
-                                }
-                            }
-                        }
-                    } else {
-                        // Otherwise, assume it is directly an array of compile command objects.
-                        compile_commands = arr.clone();
+
+    #[test]
+    fn env_config_wins_when_no_explicit_input() {
+        assert_eq!(
+            resolve_input_from(None, Some("env.json".to_string()), Some("discovered.json".to_string())),
+            "env.json"
+        );
+    }
+
+    #[test]
+    fn discovered_path_wins_when_no_explicit_or_env() {
+        assert_eq!(resolve_input_from(None, None, Some("discovered.json".to_string())), "discovered.json");
+    }
+
+    #[test]
+    fn falls_back_to_historical_default_when_nothing_resolved() {
+        assert_eq!(resolve_input_from(None, None, None), DEFAULT_CONFIG_RELATIVE_PATH);
+    }
+}
+
+/// Shape used for entries produced in generate mode.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// A single `command` string with the arguments joined by spaces, without
+    /// shell quoting (the historical default).
+    Command,
+    /// A `arguments` array plus an `output` field, no shell re-splitting needed.
+    Arguments,
+}
+
+/// The root of a `c_cpp_properties.json` document.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CppProperties {
+    configurations: Vec<Configuration>,
+}
+
+/// A single entry of the `configurations` array. Only the fields this tool
+/// acts on are modeled; unrecognized fields (`intelliSenseMode`, `browse`,
+/// etc.) are ignored by serde rather than rejected.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Configuration {
+    name: Option<String>,
+    compiler_path: Option<String>,
+    #[serde(default)]
+    include_path: Vec<String>,
+    #[serde(default)]
+    defines: Vec<String>,
+    c_standard: Option<String>,
+    cpp_standard: Option<String>,
+    #[serde(default)]
+    forced_include: Vec<String>,
+    #[serde(default)]
+    compiler_args: Vec<String>,
+    compile_commands: Option<Value>,
+}
+
+/// Compute the object-file path a compiler would emit for `source`, e.g.
+/// `src/foo.cpp` -> `src/foo.o`.
+fn output_for_source(source: &str) -> String {
+    Path::new(source)
+        .with_extension("o")
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod output_and_format_tests {
+    use super::*;
+
+    #[test]
+    fn output_for_source_replaces_extension_with_o() {
+        assert_eq!(output_for_source("src/foo.cpp"), "src/foo.o");
+        assert_eq!(output_for_source("main.c"), "main.o");
+    }
+
+    #[test]
+    fn output_for_source_appends_extension_when_source_has_none() {
+        assert_eq!(output_for_source("main"), "main.o");
+    }
+
+    fn test_config() -> Configuration {
+        Configuration {
+            name: Some("Test".to_string()),
+            compiler_path: Some("/usr/bin/gcc".to_string()),
+            include_path: vec!["/usr/include".to_string()],
+            defines: vec!["DEBUG".to_string()],
+            c_standard: Some("c11".to_string()),
+            cpp_standard: Some("c++17".to_string()),
+            forced_include: vec!["prefix.h".to_string()],
+            compiler_args: vec!["-Wall".to_string()],
+            compile_commands: None,
+        }
+    }
+
+    fn args_for(format: Format, sources: Vec<String>) -> Args {
+        Args {
+            input: Some("/proj/.vscode/c_cpp_properties.json".to_string()),
+            output: "./compile_commands.json".to_string(),
+            format,
+            config: None,
+            all: false,
+            workspace: None,
+            sources,
+        }
+    }
+
+    #[test]
+    fn format_arguments_splits_flags_into_array_entries() {
+        let config = test_config();
+        let args = args_for(Format::Arguments, vec!["main.c".to_string()]);
+        let entries = process_config(&config, &args);
+        let entry = &entries[0];
+        let arguments: Vec<&str> = entry["arguments"]
+            .as_array()
+            .expect("arguments should be an array")
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        assert_eq!(arguments[0], "/usr/bin/gcc");
+        assert!(arguments.contains(&"-I/usr/include"));
+        assert!(arguments.contains(&"-DDEBUG"));
+        assert!(arguments.contains(&"-Wall"));
+    }
+
+    #[test]
+    fn format_arguments_places_forced_include_as_a_flag_value_pair() {
+        let config = test_config();
+        let args = args_for(Format::Arguments, vec!["main.c".to_string()]);
+        let entries = process_config(&config, &args);
+        let arguments: Vec<&str> = entries[0]["arguments"]
+            .as_array()
+            .expect("arguments should be an array")
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        let include_pos = arguments.iter().position(|&a| a == "-include").expect("-include present");
+        assert_eq!(arguments[include_pos + 1], "prefix.h");
+    }
+
+    #[test]
+    fn format_arguments_sets_output_via_with_extension() {
+        let config = test_config();
+        let args = args_for(Format::Arguments, vec!["src/main.c".to_string()]);
+        let entries = process_config(&config, &args);
+        assert_eq!(entries[0]["output"], "src/main.o");
+        let arguments: Vec<&str> = entries[0]["arguments"]
+            .as_array()
+            .expect("arguments should be an array")
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        let o_pos = arguments.iter().position(|&a| a == "-o").expect("-o present");
+        assert_eq!(arguments[o_pos + 1], "src/main.o");
+    }
+}
+
+/// Resolve the `${workspaceFolder}` value to use: the explicit `--workspace`
+/// override if given, otherwise the project root implied by the input file.
+/// `c_cpp_properties.json` conventionally lives in `<workspaceFolder>/.vscode`,
+/// so the workspace is the parent of that `.vscode` directory, not the parent
+/// of the file itself.
+fn resolve_workspace(args: &Args) -> String {
+    args.workspace.clone().unwrap_or_else(|| {
+        let input_dir = Path::new(args.input_path()).parent();
+        let workspace_dir = match input_dir {
+            Some(dir) if dir.file_name().map(|n| n == ".vscode").unwrap_or(false) => dir.parent(),
+            other => other,
+        };
+        workspace_dir
+            .map(|p| p.to_string_lossy().into_owned())
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| ".".to_string())
+    })
+}
+
+/// Expand the VSCode-style `${workspaceFolder}`, `${workspaceFolderBasename}`,
+/// and `${env:NAME}` tokens that appear in `c_cpp_properties.json` string
+/// fields. `${default}` has no meaningful value outside the editor, so it is
+/// dropped.
+fn expand_vars(text: &str, workspace: &str) -> String {
+    let basename = Path::new(workspace)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut out = String::with_capacity(text.len());
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '$' && chars.get(i + 1) == Some(&'{') {
+            if let Some(end) = chars[i..].iter().position(|&c| c == '}').map(|p| i + p) {
+                let token: String = chars[i + 2..end].iter().collect();
+                if token == "workspaceFolder" {
+                    out.push_str(workspace);
+                } else if token == "workspaceFolderBasename" {
+                    out.push_str(&basename);
+                } else if token == "default" {
+                    // No editor default to fall back to; contributes nothing.
+                } else if let Some(name) = token.strip_prefix("env:") {
+                    match env::var(name) {
+                        Ok(value) => out.push_str(&value),
+                        Err(_) => eprintln!("Warning: environment variable '{}' is not set.", name),
                     }
                 } else {
-                    eprintln!("Error: The 'compileCommands' field is not an array.");
-                    process::exit(1);
+                    // Unknown token: leave it verbatim rather than guess.
+                    out.push_str(&chars[i..=end].iter().collect::<String>());
                 }
+                i = end + 1;
+                continue;
             }
-            // Otherwise, if no "compileCommands" field exists, use generate mode.
-            else {
-                if args.sources.is_empty() {
-                    eprintln!("Error: No compileCommands field found and no source files were provided.");
-                    process::exit(1);
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod variable_expansion_tests {
+    use super::*;
+
+    #[test]
+    fn expand_vars_substitutes_workspace_folder() {
+        assert_eq!(expand_vars("${workspaceFolder}/include", "/proj"), "/proj/include");
+    }
+
+    #[test]
+    fn expand_vars_substitutes_workspace_folder_basename() {
+        assert_eq!(expand_vars("${workspaceFolderBasename}", "/home/user/proj"), "proj");
+    }
+
+    #[test]
+    fn expand_vars_substitutes_env_var() {
+        std::env::set_var("GENCOMP_TEST_VAR", "value");
+        assert_eq!(expand_vars("${env:GENCOMP_TEST_VAR}", "/proj"), "value");
+        std::env::remove_var("GENCOMP_TEST_VAR");
+    }
+
+    #[test]
+    fn expand_vars_warns_and_substitutes_empty_for_unset_env_var() {
+        std::env::remove_var("GENCOMP_TEST_VAR_UNSET");
+        assert_eq!(expand_vars("${env:GENCOMP_TEST_VAR_UNSET}", "/proj"), "");
+    }
+
+    #[test]
+    fn expand_vars_drops_default_token() {
+        assert_eq!(expand_vars("${default}", "/proj"), "");
+    }
+
+    #[test]
+    fn expand_vars_leaves_unknown_tokens_verbatim() {
+        assert_eq!(expand_vars("${unknownToken}", "/proj"), "${unknownToken}");
+    }
+
+    #[test]
+    fn expand_vars_handles_multiple_tokens_in_one_string() {
+        assert_eq!(
+            expand_vars("${workspaceFolder}/${workspaceFolderBasename}/include", "/home/user/proj"),
+            "/home/user/proj/proj/include"
+        );
+    }
+
+    fn args_with_input(input: &str) -> Args {
+        Args {
+            input: Some(input.to_string()),
+            output: "./compile_commands.json".to_string(),
+            format: Format::Command,
+            config: None,
+            all: false,
+            workspace: None,
+            sources: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn resolve_workspace_uses_parent_of_dot_vscode_directory() {
+        let args = args_with_input("/proj/.vscode/c_cpp_properties.json");
+        assert_eq!(resolve_workspace(&args), "/proj");
+    }
+
+    #[test]
+    fn resolve_workspace_falls_back_to_input_parent_outside_dot_vscode() {
+        let args = args_with_input("/proj/c_cpp_properties.json");
+        assert_eq!(resolve_workspace(&args), "/proj");
+    }
+
+    #[test]
+    fn resolve_workspace_honors_explicit_override() {
+        let mut args = args_with_input("/proj/.vscode/c_cpp_properties.json");
+        args.workspace = Some("/elsewhere".to_string());
+        assert_eq!(resolve_workspace(&args), "/elsewhere");
+    }
+}
+
+/// Strip `//` and `/*…*/` comments from `text`, leaving string contents
+/// untouched (so an include path like `"C:/proj/src"` or a `//`-containing
+/// define survives intact).
+fn strip_comments(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            out.push(c);
+            if c == '\\' && i + 1 < chars.len() {
+                out.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            if c == '"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '"' {
+            in_string = true;
+            out.push(c);
+            i += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            i += 2;
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            continue;
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
+/// Drop trailing commas before `}` or `]`, again leaving string contents
+/// untouched. JSONC (as used by `c_cpp_properties.json`) permits these;
+/// `serde_json` does not.
+fn strip_trailing_commas(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            out.push(c);
+            if c == '\\' && i + 1 < chars.len() {
+                out.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            if c == '"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '"' {
+            in_string = true;
+            out.push(c);
+            i += 1;
+            continue;
+        }
+        if c == ',' {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j].is_whitespace() {
+                j += 1;
+            }
+            if j < chars.len() && (chars[j] == '}' || chars[j] == ']') {
+                i += 1;
+                continue;
+            }
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
+/// Parse a JSONC document (JSON with `//`/`/*…*/` comments and trailing
+/// commas), the format used by `c_cpp_properties.json` and referenced
+/// `compileCommands` files alike.
+fn parse_jsonc(text: &str) -> serde_json::Result<Value> {
+    let without_comments = strip_comments(text);
+    let without_trailing_commas = strip_trailing_commas(&without_comments);
+    serde_json::from_str(&without_trailing_commas)
+}
+
+#[cfg(test)]
+mod jsonc_tests {
+    use super::*;
+
+    #[test]
+    fn strip_comments_removes_line_comments() {
+        assert_eq!(strip_comments("{\"a\": 1 // trailing\n}"), "{\"a\": 1 \n}");
+    }
+
+    #[test]
+    fn strip_comments_removes_multiline_block_comments() {
+        assert_eq!(strip_comments("{\"a\":/* one\ntwo */ 1}"), "{\"a\": 1}");
+    }
+
+    #[test]
+    fn strip_comments_leaves_slashes_inside_strings_alone() {
+        assert_eq!(strip_comments("{\"path\": \"C:/proj/src\"}"), "{\"path\": \"C:/proj/src\"}");
+        assert_eq!(strip_comments("{\"define\": \"A//B\"}"), "{\"define\": \"A//B\"}");
+    }
+
+    #[test]
+    fn strip_comments_respects_escaped_quotes() {
+        assert_eq!(strip_comments(r#"{"a": "he said \"hi // not a comment\""}"#), r#"{"a": "he said \"hi // not a comment\""}"#);
+    }
+
+    #[test]
+    fn strip_trailing_commas_drops_them_before_closing_brackets() {
+        assert_eq!(strip_trailing_commas("{\"a\": [1, 2,], \"b\": 3,}"), "{\"a\": [1, 2], \"b\": 3}");
+    }
+
+    #[test]
+    fn strip_trailing_commas_leaves_commas_inside_strings_alone() {
+        assert_eq!(strip_trailing_commas(r#"{"a": "1,2,"}"#), r#"{"a": "1,2,"}"#);
+    }
+
+    #[test]
+    fn parse_jsonc_handles_comments_and_trailing_commas_together() {
+        let text = r#"{
+            // a config
+            "configurations": [
+                {
+                    "name": "Linux", /* platform */
+                    "defines": ["A", "B",],
+                },
+            ],
+        }"#;
+        let value = parse_jsonc(text).expect("should parse");
+        assert_eq!(value["configurations"][0]["name"], "Linux");
+        assert_eq!(value["configurations"][0]["defines"][1], "B");
+    }
+}
+
+/// A key used to deduplicate compile command entries produced from different
+/// configurations in `--all` mode.
+fn entry_dedup_key(entry: &Value) -> (String, String) {
+    let file = entry.get("file").and_then(Value::as_str).unwrap_or("").to_string();
+    let command = entry
+        .get("command")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| entry.get("arguments").map(|v| v.to_string()).unwrap_or_default());
+    (file, command)
+}
+
+/// Run every configuration through `process_config` and concatenate the
+/// results, dropping entries whose `entry_dedup_key` was already seen. Used
+/// by `--all` mode.
+fn process_all(configs: &[Configuration], args: &Args) -> Vec<Value> {
+    let mut seen = std::collections::HashSet::new();
+    let mut compile_commands = Vec::new();
+    for config in configs {
+        for entry in process_config(config, args) {
+            if seen.insert(entry_dedup_key(&entry)) {
+                compile_commands.push(entry);
+            }
+        }
+    }
+    compile_commands
+}
+
+#[cfg(test)]
+mod dedup_tests {
+    use super::*;
+
+    #[test]
+    fn entry_dedup_key_uses_file_and_command_string() {
+        let entry = json!({"directory": "/proj", "command": "gcc -c main.c", "file": "main.c"});
+        assert_eq!(entry_dedup_key(&entry), ("main.c".to_string(), "gcc -c main.c".to_string()));
+    }
+
+    #[test]
+    fn entry_dedup_key_uses_arguments_when_no_command() {
+        let entry = json!({"directory": "/proj", "arguments": ["gcc", "-c", "main.c"], "file": "main.c"});
+        let arguments_key = json!(["gcc", "-c", "main.c"]).to_string();
+        assert_eq!(entry_dedup_key(&entry), ("main.c".to_string(), arguments_key));
+    }
+
+    #[test]
+    fn entry_dedup_key_differs_for_different_commands() {
+        let a = json!({"command": "gcc -c main.c", "file": "main.c"});
+        let b = json!({"command": "clang -c main.c", "file": "main.c"});
+        assert_ne!(entry_dedup_key(&a), entry_dedup_key(&b));
+    }
+
+    fn test_config(name: &str) -> Configuration {
+        Configuration {
+            name: Some(name.to_string()),
+            compiler_path: Some("/usr/bin/gcc".to_string()),
+            include_path: Vec::new(),
+            defines: Vec::new(),
+            c_standard: Some("c11".to_string()),
+            cpp_standard: None,
+            forced_include: Vec::new(),
+            compiler_args: Vec::new(),
+            compile_commands: None,
+        }
+    }
+
+    #[test]
+    fn process_all_dedups_identical_entries_from_two_configs() {
+        let configs = vec![test_config("Linux"), test_config("Linux Clone")];
+        let args = Args {
+            input: Some("/proj/.vscode/c_cpp_properties.json".to_string()),
+            output: "./compile_commands.json".to_string(),
+            format: Format::Command,
+            config: None,
+            all: true,
+            workspace: None,
+            sources: vec!["main.c".to_string()],
+        };
+        let entries = process_all(&configs, &args);
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn process_all_keeps_distinct_entries_from_different_configs() {
+        let mut second = test_config("Clang");
+        second.compiler_path = Some("/usr/bin/clang".to_string());
+        let configs = vec![test_config("Linux"), second];
+        let args = Args {
+            input: Some("/proj/.vscode/c_cpp_properties.json".to_string()),
+            output: "./compile_commands.json".to_string(),
+            format: Format::Command,
+            config: None,
+            all: true,
+            workspace: None,
+            sources: vec!["main.c".to_string()],
+        };
+        let entries = process_all(&configs, &args);
+        assert_eq!(entries.len(), 2);
+    }
+}
+
+/// Is `source` a C (as opposed to C++) translation unit, judging by extension?
+fn is_c_source(source: &str) -> bool {
+    Path::new(source)
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("c"))
+        .unwrap_or(false)
+}
+
+/// Run merge-or-generate mode against a single `configuration`, returning the
+/// compile command entries it produces.
+fn process_config(config: &Configuration, args: &Args) -> Vec<Value> {
+    let mut compile_commands = Vec::new();
+
+    // If the configuration contains a "compileCommands" key, use merge mode.
+    if let Some(cc_field) = &config.compile_commands {
+        // Two cases:
+        // 1. If cc_field is an array and its first element is a string,
+        //    treat each element as a file path.
+        // 2. Otherwise, assume cc_field is directly an array of compile command objects.
+        if let Some(arr) = cc_field.as_array() {
+            if !arr.is_empty() && arr[0].is_string() {
+                // Each element is a file path.
+                for file_val in arr {
+                    if let Some(path_str) = file_val.as_str() {
+                        if !Path::new(path_str).exists() {
+                            eprintln!("Warning: Referenced file {} does not exist. Skipping.", path_str);
+                            continue;
+                        }
+                        let file_content = fs::read_to_string(path_str).unwrap_or_else(|err| {
+                            eprintln!("Error reading {}: {}", path_str, err);
+                            process::exit(1);
+                        });
+                        let cc_entries: Value = parse_jsonc(&file_content).unwrap_or_else(|err| {
+                            eprintln!("Error parsing JSON from {}: {}", path_str, err);
+                            process::exit(1);
+                        });
+                        if let Some(entries_arr) = cc_entries.as_array() {
+                            compile_commands.extend(entries_arr.clone());
+                        } else {
+                            eprintln!("Error: {} does not contain a JSON array.", path_str);
+                            process::exit(1);
+                        }
+                    }
                 }
-                // Get necessary fields from the configuration.
-                let compiler_path = config.get("compilerPath").and_then(Value::as_str).unwrap_or_else(|| {
-                    eprintln!("Error: 'compilerPath' not found in configuration.");
-                    process::exit(1);
-                });
-                let include_paths = config
-                    .get("includePath")
-                    .and_then(Value::as_array)
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(Value::as_str)
-                            .collect::<Vec<&str>>()
-                    })
-                    .unwrap_or_default();
-                let defines = config
-                    .get("defines")
-                    .and_then(Value::as_array)
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(Value::as_str)
-                            .collect::<Vec<&str>>()
-                    })
-                    .unwrap_or_default();
-                let cpp_standard = config.get("cppStandard").and_then(Value::as_str).unwrap_or("");
-
-                // Get current working directory.
-                let current_dir = env::current_dir()
-                    .ok()
-                    .and_then(|p| p.to_str().map(|s| s.to_string()))
-                    .unwrap_or_else(|| ".".to_string());
-
-                // For each source file, generate a compile command.
-                for source in args.sources.iter() {
+            } else {
+                // Otherwise, assume it is directly an array of compile command objects.
+                compile_commands = arr.clone();
+            }
+        } else {
+            eprintln!("Error: The 'compileCommands' field is not an array.");
+            process::exit(1);
+        }
+    }
+    // Otherwise, if no "compileCommands" field exists, use generate mode.
+    else {
+        if args.sources.is_empty() {
+            eprintln!("Error: No compileCommands field found and no source files were provided.");
+            process::exit(1);
+        }
+        let workspace = resolve_workspace(args);
+
+        // Get necessary fields from the configuration, expanding
+        // ${workspaceFolder}-style variables along the way.
+        let compiler_path = config.compiler_path.as_deref().unwrap_or_else(|| {
+            eprintln!("Error: 'compilerPath' not found in configuration.");
+            process::exit(1);
+        });
+        let compiler_path = expand_vars(compiler_path, &workspace);
+        let include_paths: Vec<String> = config
+            .include_path
+            .iter()
+            .map(|p| expand_vars(p, &workspace))
+            .collect();
+        let defines: Vec<String> = config.defines.iter().map(|d| expand_vars(d, &workspace)).collect();
+        let forced_includes: Vec<String> = config
+            .forced_include
+            .iter()
+            .map(|p| expand_vars(p, &workspace))
+            .collect();
+        let compiler_args: Vec<String> = config
+            .compiler_args
+            .iter()
+            .map(|a| expand_vars(a, &workspace))
+            .collect();
+        let c_standard = config.c_standard.as_deref().unwrap_or("");
+        let c_standard = expand_vars(c_standard, &workspace);
+        let cpp_standard = config.cpp_standard.as_deref().unwrap_or("");
+        let cpp_standard = expand_vars(cpp_standard, &workspace);
+
+        // Get current working directory.
+        let current_dir = env::current_dir()
+            .ok()
+            .and_then(|p| p.to_str().map(|s| s.to_string()))
+            .unwrap_or_else(|| ".".to_string());
+
+        // For each source file, generate a compile command.
+        for source in args.sources.iter() {
+            let std_value = if is_c_source(source) { &c_standard } else { &cpp_standard };
+            match args.format {
+                Format::Command => {
                     let include_flags = include_paths
                         .iter()
                         .map(|path| format!("-I{}", path))
@@ -160,9 +778,21 @@ fn main() {
                         .map(|d| format!("-D{}", d))
                         .collect::<Vec<_>>()
                         .join(" ");
+                    let forced_include_flags = forced_includes
+                        .iter()
+                        .map(|path| format!("-include {}", path))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let compiler_args_str = compiler_args.join(" ");
                     let command = format!(
-                        "{} {} {} --std={} -c {}",
-                        compiler_path, include_flags, define_flags, cpp_standard, source
+                        "{} {} {} {} {} --std={} -c {}",
+                        compiler_path,
+                        include_flags,
+                        define_flags,
+                        forced_include_flags,
+                        compiler_args_str,
+                        std_value,
+                        source
                     );
                     compile_commands.push(json!({
                         "directory": current_dir,
@@ -170,13 +800,194 @@ fn main() {
                         "file": source
                     }));
                 }
+                Format::Arguments => {
+                    let mut arguments = vec![compiler_path.to_string()];
+                    arguments.extend(include_paths.iter().map(|path| format!("-I{}", path)));
+                    arguments.extend(defines.iter().map(|d| format!("-D{}", d)));
+                    for path in &forced_includes {
+                        arguments.push("-include".to_string());
+                        arguments.push(path.clone());
+                    }
+                    arguments.extend(compiler_args.iter().cloned());
+                    arguments.push(format!("--std={}", std_value));
+                    arguments.push("-c".to_string());
+                    arguments.push(source.clone());
+                    let output = output_for_source(source);
+                    arguments.push("-o".to_string());
+                    arguments.push(output.clone());
+                    compile_commands.push(json!({
+                        "directory": current_dir,
+                        "arguments": arguments,
+                        "file": source,
+                        "output": output
+                    }));
+                }
             }
+        }
+    }
+
+    compile_commands
+}
+
+#[cfg(test)]
+mod process_config_tests {
+    use super::*;
+
+    fn test_config() -> Configuration {
+        Configuration {
+            name: Some("Test".to_string()),
+            compiler_path: Some("/usr/bin/gcc".to_string()),
+            include_path: Vec::new(),
+            defines: Vec::new(),
+            c_standard: Some("c11".to_string()),
+            cpp_standard: Some("c++17".to_string()),
+            forced_include: vec!["prefix.h".to_string()],
+            compiler_args: vec!["-Wall".to_string()],
+            compile_commands: None,
+        }
+    }
+
+    fn args_for(sources: Vec<String>) -> Args {
+        Args {
+            input: Some("/proj/.vscode/c_cpp_properties.json".to_string()),
+            output: "./compile_commands.json".to_string(),
+            format: Format::Command,
+            config: None,
+            all: false,
+            workspace: None,
+            sources,
+        }
+    }
+
+    #[test]
+    fn c_source_uses_c_standard() {
+        let config = test_config();
+        let args = args_for(vec!["main.c".to_string()]);
+        let entries = process_config(&config, &args);
+        assert!(entries[0]["command"].as_str().unwrap().contains("--std=c11"));
+    }
+
+    #[test]
+    fn cpp_source_uses_cpp_standard() {
+        let config = test_config();
+        let args = args_for(vec!["main.cpp".to_string()]);
+        let entries = process_config(&config, &args);
+        assert!(entries[0]["command"].as_str().unwrap().contains("--std=c++17"));
+    }
+
+    #[test]
+    fn forced_include_emits_include_flag_with_path() {
+        let config = test_config();
+        let args = args_for(vec!["main.c".to_string()]);
+        let entries = process_config(&config, &args);
+        assert!(entries[0]["command"].as_str().unwrap().contains("-include prefix.h"));
+    }
+
+    #[test]
+    fn compiler_args_are_appended_verbatim() {
+        let config = test_config();
+        let args = args_for(vec!["main.c".to_string()]);
+        let entries = process_config(&config, &args);
+        assert!(entries[0]["command"].as_str().unwrap().contains("-Wall"));
+    }
+
+    #[test]
+    fn merge_mode_passes_through_embedded_entry_array() {
+        let mut config = test_config();
+        config.compile_commands = Some(json!([
+            {"directory": "/proj", "command": "gcc -c main.c", "file": "main.c"}
+        ]));
+        let args = args_for(Vec::new());
+        let entries = process_config(&config, &args);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["file"], "main.c");
+    }
+
+    #[test]
+    fn merge_mode_reads_entries_from_referenced_files() {
+        let path = std::env::temp_dir().join("gencomp_process_config_merge_test.json");
+        fs::write(&path, r#"[{"directory": "/proj", "command": "gcc -c main.c", "file": "main.c"}]"#).unwrap();
+
+        let mut config = test_config();
+        config.compile_commands = Some(json!([path.to_string_lossy().into_owned()]));
+        let args = args_for(Vec::new());
+        let entries = process_config(&config, &args);
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["file"], "main.c");
+    }
+}
+
+fn main() {
+    let mut args = Args::parse();
+    args.input = Some(resolve_input(args.input.take()));
+
+    // Read the input file.
+    if !Path::new(args.input_path()).exists() {
+        eprintln!("Error: The file {} does not exist.", args.input_path());
+        process::exit(1);
+    }
+    let content = fs::read_to_string(args.input_path()).unwrap_or_else(|err| {
+        eprintln!("Error reading {}: {}", args.input_path(), err);
+        process::exit(1);
+    });
+    // Parse the JSONC.
+    let data: Value = parse_jsonc(&content).unwrap_or_else(|err| {
+        eprintln!("Error parsing JSON from {}: {}", args.input_path(), err);
+        process::exit(1);
+    });
+
+    // Our final compile commands will be collected here.
+    let compile_commands: Vec<Value>;
+
+    // If the top-level JSON is an array, assume it’s already a list of compile commands.
+    if let Some(arr) = data.as_array() {
+        compile_commands = arr.clone();
+    }
+    // Otherwise, if it is an object, it must match the c_cpp_properties.json schema.
+    else if data.is_object() {
+        let props: CppProperties = serde_json::from_value(data).unwrap_or_else(|err| {
+            eprintln!("Error: {} does not match the c_cpp_properties.json schema: {}", args.input_path(), err);
+            process::exit(1);
+        });
+        let configs = &props.configurations;
+        if configs.is_empty() {
+            eprintln!("Error: No configurations found in {}.", args.input_path());
+            process::exit(1);
+        }
+
+        if args.all {
+            compile_commands = process_all(configs, &args);
+        } else if let Some(name) = &args.config {
+            let config = configs
+                .iter()
+                .find(|c| c.name.as_deref() == Some(name.as_str()))
+                .unwrap_or_else(|| {
+                    let available: Vec<&str> = configs.iter().filter_map(|c| c.name.as_deref()).collect();
+                    eprintln!(
+                        "Error: No configuration named '{}' in {}. Available: {}.",
+                        name,
+                        args.input_path(),
+                        available.join(", ")
+                    );
+                    process::exit(1);
+                });
+            compile_commands = process_config(config, &args);
+        } else if configs.len() == 1 {
+            compile_commands = process_config(&configs[0], &args);
         } else {
-            eprintln!("Error: 'configurations' key not found in {}.", args.input);
+            let available: Vec<&str> = configs.iter().filter_map(|c| c.name.as_deref()).collect();
+            eprintln!(
+                "Error: {} has more than one configuration. Pass --config <NAME> or --all. Available: {}.",
+                args.input_path(),
+                available.join(", ")
+            );
             process::exit(1);
         }
     } else {
-        eprintln!("Error: Unexpected JSON structure in {}.", args.input);
+        eprintln!("Error: Unexpected JSON structure in {}.", args.input_path());
         process::exit(1);
     }
 